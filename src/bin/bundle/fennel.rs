@@ -0,0 +1,189 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use mlua::{Function, Lua, Table};
+
+/// Env var that overrides the path to `fennel.lua` used for the embedded compiler.
+const FENNEL_PATH_ENV: &str = "BUNDLE_FENNEL_PATH";
+
+/// Compiles Fennel source to Lua, either through an in-process `fennel.lua` (preferred)
+/// or by shelling out to the `fennel` CLI when embedding isn't available.
+pub enum Fennel {
+    Embedded(FennelCompiler),
+    Cli,
+}
+
+impl Fennel {
+    /// Resolve `fennel_path` (from `build.toml` or the `BUNDLE_FENNEL_PATH` env var) and
+    /// try to embed it. Falls back to the `fennel` CLI if neither is set, or if the
+    /// configured path can't be loaded.
+    pub fn resolve(fennel_path: Option<&str>) -> Self {
+        let Some(path) = fennel_path
+            .map(PathBuf::from)
+            .or_else(|| std::env::var(FENNEL_PATH_ENV).ok().map(PathBuf::from))
+        else {
+            return Fennel::Cli;
+        };
+
+        match FennelCompiler::new(&path) {
+            Ok(compiler) => Fennel::Embedded(compiler),
+            Err(err) => {
+                eprintln!(
+                    "warning: could not embed fennel from `{}` ({err}), falling back to the `fennel` CLI",
+                    path.display()
+                );
+                Fennel::Cli
+            }
+        }
+    }
+
+    pub fn compile(&self, source: &str, filename: &str) -> Result<String, String> {
+        match self {
+            Fennel::Embedded(compiler) => compiler.compile(source, filename),
+            Fennel::Cli => compile_with_cli(source, filename),
+        }
+    }
+
+    /// The compiler's reported version, as a raw string (e.g. `"1.4.2-dev"`).
+    pub fn version(&self) -> Result<String, String> {
+        match self {
+            Fennel::Embedded(compiler) => compiler.version(),
+            Fennel::Cli => version_from_cli(),
+        }
+    }
+}
+
+/// An in-process Fennel compiler backed by a single `mlua::Lua` state, shared across
+/// every `.fnl` file in a build.
+pub struct FennelCompiler {
+    lua: Lua,
+}
+
+impl FennelCompiler {
+    pub fn new(fennel_path: &Path) -> mlua::Result<Self> {
+        let lua = Lua::new();
+
+        let package: Table = lua.globals().get("package")?;
+        let existing_path: String = package.get("path")?;
+        let fennel_dir = fennel_path.parent().unwrap_or_else(|| Path::new("."));
+        package.set(
+            "path",
+            format!("{}/?.lua;{existing_path}", fennel_dir.display()),
+        )?;
+
+        let fennel: Table = lua.load("return require(\"fennel\")").eval()?;
+        lua.globals().set("fennel", fennel)?;
+
+        Ok(Self { lua })
+    }
+
+    /// Compile a single Fennel source string to Lua, tagging the chunk with `filename` so
+    /// compile errors can point back at the original file.
+    pub fn compile(&self, source: &str, filename: &str) -> Result<String, String> {
+        let fennel: Table = self
+            .lua
+            .globals()
+            .get("fennel")
+            .map_err(|err| err.to_string())?;
+        let compile_string: Function = fennel
+            .get("compileString")
+            .map_err(|err| err.to_string())?;
+
+        let opts = self.lua.create_table().map_err(|err| err.to_string())?;
+        opts.set("filename", filename).map_err(|err| err.to_string())?;
+
+        compile_string
+            .call::<String>((source, opts))
+            .map_err(|err| format!("failed to compile `{filename}`: {err}"))
+    }
+
+    pub fn version(&self) -> Result<String, String> {
+        let fennel: Table = self
+            .lua
+            .globals()
+            .get("fennel")
+            .map_err(|err| err.to_string())?;
+
+        fennel.get::<String>("version").map_err(|err| err.to_string())
+    }
+}
+
+fn compile_with_cli(source: &str, filename: &str) -> Result<String, String> {
+    let mut fennel = Command::new("fennel")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .arg("--compile")
+        .arg("-")
+        .spawn()
+        .map_err(|err| format!("failed to launch `fennel` for `{filename}`: {err}"))?;
+
+    write!(fennel.stdin.as_mut().unwrap(), "{source}")
+        .map_err(|err| format!("failed to write `{filename}` to fennel's stdin: {err}"))?;
+
+    let output = fennel
+        .wait_with_output()
+        .map_err(|err| format!("failed to wait on fennel for `{filename}`: {err}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "fennel failed to compile `{filename}`: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn version_from_cli() -> Result<String, String> {
+    let output = Command::new("fennel")
+        .arg("--version")
+        .output()
+        .map_err(|err| format!("failed to launch `fennel --version`: {err}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "`fennel --version` failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Extracts the first `X.Y.Z` triple from `text`, tolerating surrounding text such as a
+/// program name or a trailing git hash (e.g. `"Fennel 1.4.2-abc1234"` -> `(1, 4, 2)`).
+pub fn parse_semver(text: &str) -> Option<(u32, u32, u32)> {
+    let bytes = text.as_bytes();
+
+    'outer: for start in 0..bytes.len() {
+        let mut rest = &text[start..];
+        let mut parts = [0u32; 3];
+
+        for (index, part) in parts.iter_mut().enumerate() {
+            let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            if digits.is_empty() {
+                break;
+            }
+
+            let Ok(value) = digits.parse() else {
+                continue 'outer;
+            };
+            *part = value;
+            rest = &rest[digits.len()..];
+
+            if index < 2 {
+                if let Some(after_dot) = rest.strip_prefix('.') {
+                    rest = after_dot;
+                } else {
+                    break;
+                }
+            } else {
+                return Some((parts[0], parts[1], parts[2]));
+            }
+        }
+    }
+
+    None
+}
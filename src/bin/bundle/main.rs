@@ -1,11 +1,14 @@
-use std::{
-    io::Write,
-    path::{Path, PathBuf},
-    process::{Command, Stdio},
-};
+use std::path::{Path, PathBuf};
 
 use toml::Table;
 
+mod fennel;
+mod minify;
+mod sourcemap;
+
+use fennel::Fennel;
+use sourcemap::SourceMap;
+
 const BUILD_FILE: &str = "build.toml";
 const DEFAULT_REQUIRE_FUNCTION: &str = "require";
 
@@ -18,17 +21,44 @@ enum LuaVersion {
     Fennel,
 }
 
+impl LuaVersion {
+    /// The version-specific runtime shim prepended to the bundle. Lua 5.1 and Fennel
+    /// (which compiles down to Lua 5.1) share a prelude built around `loadstring`;
+    /// Luau gets its own, built around `load` and without `goto` support.
+    fn prelude(&self) -> &'static str {
+        match self {
+            LuaVersion::Luau => include_str!("luau.lua"),
+            LuaVersion::Default | LuaVersion::Lua51 | LuaVersion::Fennel => {
+                include_str!("lua.lua")
+            }
+        }
+    }
+
+    /// Whether a source file extension is valid for this version.
+    fn allows_extension(&self, extension: &str) -> bool {
+        match self {
+            LuaVersion::Fennel => matches!(extension, "fnl" | "lua"),
+            LuaVersion::Luau => matches!(extension, "lua" | "luau"),
+            LuaVersion::Default | LuaVersion::Lua51 => extension == "lua",
+        }
+    }
+}
+
 struct Project {
     name: String,
     output: PathBuf,
     entry_point: PathBuf,
     files: Vec<PathBuf>,
     lua_version: LuaVersion,
+    minify: bool,
+    source_map: bool,
 }
 
 struct BuildFile {
     projects: Vec<Project>,
     require_function: String,
+    fennel_path: Option<String>,
+    min_fennel_version: Option<(u32, u32, u32)>,
 }
 
 fn main() {
@@ -36,16 +66,70 @@ fn main() {
         return;
     };
 
+    let needs_fennel = build
+        .projects
+        .iter()
+        .any(|project| project.lua_version == LuaVersion::Fennel);
+
+    let fennel = if needs_fennel {
+        Fennel::resolve(build.fennel_path.as_deref())
+    } else {
+        Fennel::Cli
+    };
+
+    if needs_fennel {
+        match fennel.version() {
+            Ok(raw_version) => {
+                let Some(version) = fennel::parse_semver(&raw_version) else {
+                    eprintln!(
+                        "error: could not parse a version from fennel's reported `{raw_version}`"
+                    );
+                    std::process::exit(1);
+                };
+
+                println!("using fennel {}.{}.{}", version.0, version.1, version.2);
+
+                if let Some(min_version) = build.min_fennel_version
+                    && version < min_version
+                {
+                    eprintln!(
+                        "error: found fennel {}.{}.{}, but this build requires at least {}.{}.{}",
+                        version.0, version.1, version.2,
+                        min_version.0, min_version.1, min_version.2
+                    );
+                    std::process::exit(1);
+                }
+            }
+            Err(err) => {
+                eprintln!(
+                    "error: a project declares `lua_version = \"Fennel\"` but no usable fennel compiler was found: {err}"
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
     for project in build.projects {
-        project.build(&build.require_function);
+        project.build(&build.require_function, &fennel);
     }
 }
 
 impl Project {
-    fn build(&self, require_method: &str) {
-        let mut output = include_str!("lua.lua").to_string();
+    fn build(&self, require_method: &str, fennel: &Fennel) {
+        let mut output = self.lua_version.prelude().to_string();
+        let mut source_map = SourceMap::new();
+
+        if self.minify && self.source_map {
+            eprintln!(
+                "warning: `source_map` line numbers reflect the unminified bundle; enabling `minify` alongside it will make them inaccurate"
+            );
+        }
+
+        let mut bundle_line = output.matches('\n').count();
 
         output.push_str("\nlocal files = {");
+        bundle_line += 1; // the leading newline in the literal above
+
         for file in &self.files {
             let binding = path_without_extension(file);
             let name = binding.to_str().unwrap();
@@ -53,14 +137,51 @@ impl Project {
             let mut content = std::fs::read_to_string(file).unwrap();
             let extension = file.extension().unwrap().to_str().unwrap();
 
+            if !self.lua_version.allows_extension(extension) {
+                eprintln!(
+                    "error: `{}` has extension `.{extension}`, which isn't valid for this project's `lua_version`",
+                    file.display()
+                );
+                std::process::exit(1);
+            }
+
             if extension == "fnl" {
-                content = compile_fennel_to_lua(&content);
+                content = fennel.compile(&content, name).unwrap_or_else(|err| {
+                    eprintln!("error: {err}");
+                    std::process::exit(1);
+                });
             }
 
-            output.push_str(insert_module(name, &content, require_method, 1).as_str());
+            let module_text = insert_module(name, &content, require_method, 1);
+
+            if self.source_map {
+                if extension == "fnl" {
+                    eprintln!(
+                        "warning: `{}` is compiled from Fennel, and `source_map` can't yet map its lines back to the original `.fnl` source — tracebacks for this module will show bundle lines instead",
+                        file.display()
+                    );
+                } else {
+                    let line_count = content.lines().count();
+                    if line_count > 0 {
+                        let first_code_line = bundle_line + 1 + MODULE_HEADER_LINES;
+                        source_map.record_module(
+                            file.to_str().unwrap(),
+                            first_code_line,
+                            line_count,
+                        );
+                    }
+                }
+            }
+
+            bundle_line += module_text.matches('\n').count();
+            output.push_str(&module_text);
         }
         output.push_str("\n}\n");
 
+        if self.source_map {
+            output.push_str(&source_map.traceback_helper());
+        }
+
         output.push_str(
             insert_entry_point(
                 path_without_extension(&self.entry_point)
@@ -71,8 +192,20 @@ impl Project {
             .as_str(),
         );
 
+        if self.minify {
+            output = minify::minify(&output);
+        }
+
         std::fs::create_dir_all(&self.output).unwrap();
         std::fs::write(self.output.join(&self.name), output).unwrap();
+
+        if self.source_map {
+            std::fs::write(
+                self.output.join(format!("{}.map", &self.name)),
+                source_map.to_json(),
+            )
+            .unwrap();
+        }
     }
 }
 
@@ -86,6 +219,11 @@ impl BuildFile {
         let build = std::fs::read_to_string(BUILD_FILE).unwrap();
         let table = build.as_str().parse::<Table>().unwrap();
 
+        let default_minify = table
+            .get("minify")
+            .map(|value| value.as_bool().unwrap())
+            .unwrap_or(false);
+
         let projects = match table.get("project") {
             Some(value) => {
                 let mut projects = Vec::new();
@@ -93,7 +231,7 @@ impl BuildFile {
 
                 for value in array {
                     let table = value.as_table().unwrap();
-                    let Some(project) = parse_project(table) else {
+                    let Some(project) = parse_project(table, default_minify) else {
                         continue;
                     };
 
@@ -108,27 +246,32 @@ impl BuildFile {
             }
         };
 
+        let fennel_path = table
+            .get("fennel_path")
+            .map(|value| value.as_str().unwrap().to_string());
+
+        let min_fennel_version = match table.get("min_fennel_version") {
+            Some(value) => {
+                let raw = value.as_str().unwrap();
+                let Some(version) = fennel::parse_semver(raw) else {
+                    eprintln!("error: `min_fennel_version` is not a valid `X.Y.Z` version: {raw}");
+                    return None;
+                };
+                Some(version)
+            }
+            None => None,
+        };
+
         Some(BuildFile {
             projects,
             require_function: DEFAULT_REQUIRE_FUNCTION.into(),
+            fennel_path,
+            min_fennel_version,
         })
     }
 }
 
-fn compile_fennel_to_lua(source: &str) -> String {
-    let mut fennel = Command::new("fennel")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .arg("--compile")
-        .arg("-")
-        .spawn()
-        .expect("error: failed to launch fennel");
-
-    write!(fennel.stdin.as_mut().unwrap(), "{}", source).unwrap();
-    String::from_utf8_lossy(&fennel.wait_with_output().unwrap().stdout).to_string()
-}
-
-fn parse_project(table: &Table) -> Option<Project> {
+fn parse_project(table: &Table, default_minify: bool) -> Option<Project> {
     let name = format!(
         "{}.lua",
         match table.get("name") {
@@ -137,7 +280,7 @@ fn parse_project(table: &Table) -> Option<Project> {
         }
     );
 
-    let output = match table.get("output") {
+    let output: PathBuf = match table.get("output") {
         Some(value) => value.as_str().unwrap(),
         None => "build",
     }
@@ -164,7 +307,22 @@ fn parse_project(table: &Table) -> Option<Project> {
         None => LuaVersion::default(),
     };
 
-    let files = match table.get("files") {
+    let Some(entry_extension) = entry_point.extension().and_then(|extension| extension.to_str())
+    else {
+        eprintln!(
+            "error: the `entry_point` (`{}`) has no file extension",
+            entry_point.display()
+        );
+        return None;
+    };
+    if !lua_version.allows_extension(entry_extension) {
+        eprintln!(
+            "error: the `entry_point` has extension `.{entry_extension}`, which isn't valid for this project's `lua_version`"
+        );
+        return None;
+    }
+
+    let explicit_files = match table.get("files") {
         Some(value) => {
             let mut files = Vec::new();
             let array = value.as_array().unwrap();
@@ -183,21 +341,110 @@ fn parse_project(table: &Table) -> Option<Project> {
             files
         }
 
-        None => {
-            eprintln!("error: a project entry is missing a `files` list");
-            return None;
-        }
+        None => Vec::new(),
+    };
+
+    let detect_extensions: Vec<String> = match table.get("detect_extensions") {
+        Some(value) => value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| value.as_str().unwrap().to_string())
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let detect_folders: Vec<PathBuf> = match table.get("detect_folders") {
+        Some(value) => value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| PathBuf::from(value.as_str().unwrap()))
+            .collect(),
+        None => Vec::new(),
     };
 
+    if !detect_folders.is_empty() && detect_extensions.is_empty() {
+        eprintln!("error: a project declares `detect_folders` but no `detect_extensions`");
+        return None;
+    }
+
+    let detected_files = if detect_extensions.is_empty() {
+        Vec::new()
+    } else {
+        detect_files(&detect_folders, &detect_extensions, &output)
+    };
+
+    let mut files = explicit_files;
+    for file in detected_files {
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+
+    if files.is_empty() {
+        eprintln!(
+            "error: a project entry has no source files: provide a `files` list, `detect_extensions` + `detect_folders`, or both"
+        );
+        return None;
+    }
+
+    if !files.contains(&entry_point) {
+        eprintln!(
+            "error: the `entry_point` (`{}`) isn't included in `files`, and wasn't found under any `detect_folders` — add it to `files` so it gets bundled",
+            entry_point.display()
+        );
+        return None;
+    }
+
+    let minify = table
+        .get("minify")
+        .map(|value| value.as_bool().unwrap())
+        .unwrap_or(default_minify);
+
+    let source_map = table
+        .get("source_map")
+        .map(|value| value.as_bool().unwrap())
+        .unwrap_or(false);
+
     Some(Project {
         name,
         output,
         entry_point,
         files,
         lua_version,
+        minify,
+        source_map,
     })
 }
 
+/// Walks `folders` recursively, collecting files whose extension is in `extensions`
+/// while skipping anything under the output directory. The entry point is included if
+/// it's found here — callers dedupe against any explicit `files` list themselves.
+fn detect_files(folders: &[PathBuf], extensions: &[String], output: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let output = output.strip_prefix(".").unwrap_or(output);
+
+    for folder in folders {
+        for file in files_from_path(folder) {
+            if file.strip_prefix(".").unwrap_or(&file).starts_with(output) {
+                continue;
+            }
+
+            let Some(extension) = file.extension().and_then(|extension| extension.to_str())
+            else {
+                continue;
+            };
+
+            if extensions.iter().any(|detected| detected == extension) {
+                files.push(file);
+            }
+        }
+    }
+
+    files
+}
+
 fn files_from_path(path: &Path) -> Vec<PathBuf> {
     let mut files = Vec::new();
 
@@ -245,6 +492,12 @@ fn inject_require(code: &str, require: &str) -> String {
     )
 }
 
+/// Lines `insert_module` adds before the first line of a module's own code: the blank
+/// line and `["file"] = function(functions)` header, then the injected require binding
+/// and the blank line that follows it. Used by `Project::build` to translate a file's
+/// line numbers into bundle line numbers for the source map.
+const MODULE_HEADER_LINES: usize = 4;
+
 fn insert_module(file: &str, code: &str, require: &str, level: usize) -> String {
     let code = indent_block(inject_require(code, require), 1);
     indent_block(
@@ -0,0 +1,137 @@
+/// Runs a Lua-aware minification pass over a finished bundle: strips comments and
+/// collapses insignificant whitespace. String and long-bracket literals are left
+/// untouched throughout.
+///
+/// This intentionally does not rename local variables: a prior version of this pass
+/// shortened `local` declarations with a whole-word textual replace, which is
+/// indistinguishable from renaming table keys (`{name = "point"}`) and field accesses
+/// (`.name`) that happen to share a name with some unrelated local elsewhere in the
+/// bundle — silently corrupting those instead of the intended variable references.
+pub fn minify(source: &str) -> String {
+    strip_comments_and_collapse(source)
+}
+
+/// Removes `--` and `--[=[ ]=]` comments, strips leading/trailing whitespace from each
+/// line, and drops blank lines, all while passing string and long-bracket literals
+/// through byte-for-byte.
+fn strip_comments_and_collapse(source: &str) -> String {
+    let bytes = source.as_bytes();
+    let mut out = String::with_capacity(source.len());
+    let mut i = 0;
+    let mut at_line_start = true;
+    let mut pending_space = false;
+
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+
+        if c == '-' && bytes.get(i + 1) == Some(&b'-') {
+            let after = i + 2;
+            if let Some(level) = long_bracket_level(bytes, after) {
+                i = read_long_bracket(bytes, after, level).1;
+            } else {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            continue;
+        }
+
+        if c == '"' || c == '\'' {
+            let quote = bytes[i];
+            let start = i;
+            i += 1;
+            while i < bytes.len() && bytes[i] != quote {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(bytes.len());
+            flush_pending(&mut out, &mut pending_space);
+            out.push_str(&source[start..i]);
+            at_line_start = false;
+            continue;
+        }
+
+        if c == '[' && let Some(level) = long_bracket_level(bytes, i) {
+            let start = i;
+            i = read_long_bracket(bytes, i, level).1;
+            flush_pending(&mut out, &mut pending_space);
+            out.push_str(&source[start..i]);
+            at_line_start = false;
+            continue;
+        }
+
+        if c == '\n' {
+            if !at_line_start {
+                out.push('\n');
+            }
+            at_line_start = true;
+            pending_space = false;
+            i += 1;
+            continue;
+        }
+
+        if c == ' ' || c == '\t' {
+            if !at_line_start {
+                pending_space = true;
+            }
+            i += 1;
+            continue;
+        }
+
+        flush_pending(&mut out, &mut pending_space);
+        out.push(c);
+        at_line_start = false;
+        i += 1;
+    }
+
+    out
+}
+
+fn flush_pending(out: &mut String, pending_space: &mut bool) {
+    if *pending_space {
+        out.push(' ');
+    }
+    *pending_space = false;
+}
+
+/// If `bytes[i]` opens a long bracket (`[`, `[=`, `[==`, ...), returns its level.
+fn long_bracket_level(bytes: &[u8], i: usize) -> Option<usize> {
+    if bytes.get(i) != Some(&b'[') {
+        return None;
+    }
+
+    let mut j = i + 1;
+    let mut level = 0;
+    while bytes.get(j) == Some(&b'=') {
+        level += 1;
+        j += 1;
+    }
+
+    (bytes.get(j) == Some(&b'[')).then_some(level)
+}
+
+/// Given the index of the opening `[=*[` of a long bracket, returns its contents and the
+/// index just past the matching `]=*]` (or the end of input if it's unterminated).
+fn read_long_bracket(bytes: &[u8], start: usize, level: usize) -> (String, usize) {
+    let content_start = start + 2 + level;
+    let closer: Vec<u8> = [b']']
+        .into_iter()
+        .chain(std::iter::repeat_n(b'=', level))
+        .chain([b']'])
+        .collect();
+
+    let mut i = content_start;
+    while i + closer.len() <= bytes.len() {
+        if bytes[i..i + closer.len()] == closer[..] {
+            return (
+                String::from_utf8_lossy(&bytes[content_start..i]).to_string(),
+                i + closer.len(),
+            );
+        }
+        i += 1;
+    }
+
+    (
+        String::from_utf8_lossy(&bytes[content_start..]).to_string(),
+        bytes.len(),
+    )
+}
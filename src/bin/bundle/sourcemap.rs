@@ -0,0 +1,155 @@
+use std::rc::Rc;
+
+/// Maps lines in a bundled `output` file back to the original source file and line they
+/// came from, so runtime tracebacks can be translated back to the source tree instead of
+/// pointing at meaningless lines in the concatenated bundle.
+pub struct SourceMap {
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    bundle_line: usize,
+    file: Rc<str>,
+    original_line: usize,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Records a line-for-line mapping: `file`'s lines `1..=line_count` begin at
+    /// `first_bundle_line` (1-indexed) in the bundle.
+    ///
+    /// This assumes `file`'s line count matches the bundled text's, which only holds for
+    /// modules that are bundled as-is. Compiled Fennel doesn't meet that assumption —
+    /// `compileString` doesn't report per-line source positions, so callers should skip
+    /// Fennel modules here rather than record an incorrect mapping.
+    pub fn record_module(&mut self, file: &str, first_bundle_line: usize, line_count: usize) {
+        let file: Rc<str> = Rc::from(file);
+
+        for offset in 0..line_count {
+            self.entries.push(Entry {
+                bundle_line: first_bundle_line + offset,
+                file: Rc::clone(&file),
+                original_line: offset + 1,
+            });
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[\n");
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            json.push_str(&format!(
+                "  {{\"bundle_line\": {}, \"file\": {}, \"original_line\": {}}}",
+                entry.bundle_line,
+                escape_json_string(&entry.file),
+                entry.original_line
+            ));
+            if index + 1 < self.entries.len() {
+                json.push(',');
+            }
+            json.push('\n');
+        }
+
+        json.push(']');
+        json
+    }
+
+    /// A Lua helper that overrides `error` and `debug.traceback` to report original
+    /// source positions (using this map) instead of bundle line numbers. Meant to be
+    /// appended to the bundle after the module table but before the entry point runs, so
+    /// every already-recorded `bundle_line` still points at a line before this code.
+    pub fn traceback_helper(&self) -> String {
+        format!(
+            "\nlocal __bundle_source_map = {}\n{TRACEBACK_HELPER_BODY}",
+            self.to_lua_literal()
+        )
+    }
+
+    fn to_lua_literal(&self) -> String {
+        let mut lua = String::from("{\n");
+
+        for entry in &self.entries {
+            lua.push_str(&format!(
+                "  {{{}, {}, {}}},\n",
+                entry.bundle_line,
+                lua_string_literal(&entry.file),
+                entry.original_line
+            ));
+        }
+
+        lua.push('}');
+        lua
+    }
+}
+
+impl Default for SourceMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A small Lua runtime helper, appended to the bundle right after `__bundle_source_map`,
+/// that makes `error` and `debug.traceback` report original source positions instead of
+/// bundle line numbers.
+const TRACEBACK_HELPER_BODY: &str = r#"local function __bundle_original_position(bundle_line)
+    for _, entry in ipairs(__bundle_source_map) do
+        if entry[1] == bundle_line then
+            return entry[2], entry[3]
+        end
+    end
+    return nil, bundle_line
+end
+
+local __bundle_error = error
+function error(message, level)
+    level = level or 1
+    if type(message) == "string" and level > 0 then
+        local info = debug.getinfo(level + 1, "Sl")
+        if info then
+            local file, original_line = __bundle_original_position(info.currentline)
+            message = (file or "?") .. ":" .. (original_line or info.currentline) .. ": " .. message
+        end
+        level = 0
+    end
+    __bundle_error(message, level)
+end
+
+local __bundle_traceback = debug.traceback
+debug.traceback = function(message, level)
+    local trace = __bundle_traceback(message, level)
+    return (trace:gsub(":(%d+):", function(bundle_line)
+        local file, original_line = __bundle_original_position(tonumber(bundle_line))
+        if file then
+            return ":" .. file .. ":" .. original_line .. ":"
+        end
+        return ":" .. bundle_line .. ":"
+    end))
+end
+"#;
+
+fn escape_json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// Escapes `value` as a double-quoted Lua string literal.
+fn lua_string_literal(value: &str) -> String {
+    escape_json_string(value)
+}